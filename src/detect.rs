@@ -0,0 +1,50 @@
+use crate::language::Language;
+use crate::loader;
+
+/// Names of the grammars compiled into this binary, used to drive the
+/// `TypesBuilder` selection when `--auto` is enabled.
+pub fn enabled_language_names() -> &'static [&'static str] {
+    &[
+        #[cfg(feature = "rust")]
+        "rust",
+        #[cfg(feature = "go")]
+        "go",
+        #[cfg(feature = "javascript")]
+        "js",
+        #[cfg(feature = "typescript")]
+        "ts",
+        #[cfg(feature = "php")]
+        "php",
+    ]
+}
+
+/// Resolves the grammar that should parse a file with the given extension,
+/// the way `tokei` maps extensions to languages: built-ins first, then any
+/// loader-configured dynamic grammar.
+pub fn language_for_extension(ext: &str) -> Option<Language> {
+    builtin_for_extension(ext).or_else(|| dynamic_for_extension(ext))
+}
+
+fn builtin_for_extension(ext: &str) -> Option<Language> {
+    Some(match ext {
+        #[cfg(feature = "rust")]
+        "rs" => Language::Rust,
+        #[cfg(feature = "go")]
+        "go" => Language::Go,
+        #[cfg(feature = "javascript")]
+        "js" | "jsx" | "mjs" | "cjs" => Language::JS,
+        #[cfg(feature = "typescript")]
+        "ts" | "mts" | "cts" => Language::TS,
+        #[cfg(feature = "typescript")]
+        "tsx" => Language::TSX,
+        #[cfg(feature = "php")]
+        "php" => Language::PHP,
+        _ => return None,
+    })
+}
+
+fn dynamic_for_extension(ext: &str) -> Option<Language> {
+    let loader = loader::loader();
+    let name = loader.config().name_for_extension(ext)?;
+    loader.load(name).ok().cloned()
+}
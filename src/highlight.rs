@@ -0,0 +1,104 @@
+use std::fmt::Write as _;
+
+use ariadne::Color as AriadneColor;
+use console::Style;
+use tree_sitter::Node;
+
+/// Picks a style for a leaf token by its tree-sitter node kind, similar to
+/// how `tree-viz` styles query results with `console`: anonymous nodes
+/// (punctuation, keywords) are told apart from named ones by `named`, and
+/// named kinds are matched loosely since grammars don't share exact names
+/// for strings/comments/identifiers/numbers.
+pub fn style_for(kind: &str, named: bool) -> Style {
+    if !named {
+        return if kind.starts_with(|c: char| c.is_alphabetic()) {
+            Style::new().yellow().bold()
+        } else {
+            Style::new()
+        };
+    }
+
+    if kind.contains("comment") {
+        Style::new().dim()
+    } else if kind.contains("string") || kind.contains("char") {
+        Style::new().green()
+    } else if kind.contains("number") || kind.contains("int") || kind.contains("float") {
+        Style::new().magenta()
+    } else if kind.contains("identifier") {
+        Style::new().cyan()
+    } else {
+        Style::new()
+    }
+}
+
+/// The `ariadne::Color` equivalent of [`style_for`], used to color a
+/// `--pretty` label's underline by the kind of node it captured.
+pub fn ariadne_color(kind: &str, named: bool) -> AriadneColor {
+    if !named {
+        return if kind.starts_with(|c: char| c.is_alphabetic()) {
+            AriadneColor::Yellow
+        } else {
+            AriadneColor::Primary
+        };
+    }
+
+    if kind.contains("comment") {
+        AriadneColor::BrightBlack
+    } else if kind.contains("string") || kind.contains("char") {
+        AriadneColor::Green
+    } else if kind.contains("number") || kind.contains("int") || kind.contains("float") {
+        AriadneColor::Magenta
+    } else if kind.contains("identifier") {
+        AriadneColor::Cyan
+    } else {
+        AriadneColor::Primary
+    }
+}
+
+/// Every leaf (childless) descendant of `node`, in source order. Grammars
+/// don't emit nodes for the whitespace between tokens, so callers that
+/// reconstruct text from these need to fill the gaps from `src` themselves.
+pub fn leaves<'tree>(node: Node<'tree>) -> Vec<Node<'tree>> {
+    let mut out = Vec::new();
+    collect_leaves(node, &mut out);
+    out
+}
+
+fn collect_leaves<'tree>(node: Node<'tree>, out: &mut Vec<Node<'tree>>) {
+    if node.child_count() == 0 {
+        out.push(node);
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        collect_leaves(child, out);
+    }
+}
+
+/// Renders `node`'s text with each of its token leaves styled by kind,
+/// rather than painting the whole (possibly composite) captured node with a
+/// single color.
+pub fn highlight_text(node: Node, src: &[u8]) -> String {
+    let mut out = String::new();
+    let mut pos = node.start_byte();
+
+    for leaf in leaves(node) {
+        if leaf.start_byte() > pos {
+            out.push_str(&String::from_utf8_lossy(&src[pos..leaf.start_byte()]));
+        }
+
+        if let Ok(text) = leaf.utf8_text(src) {
+            let styled = style_for(leaf.kind(), leaf.is_named()).apply_to(text);
+            let _ = write!(out, "{styled}");
+        }
+
+        pos = leaf.end_byte();
+    }
+
+    if pos < node.end_byte() {
+        out.push_str(&String::from_utf8_lossy(&src[pos..node.end_byte()]));
+    }
+
+    out
+}
@@ -1,7 +1,10 @@
-use clap::ValueEnum;
+use std::str::FromStr;
+
 use tree_sitter::Language as TSLanguage;
 
-#[derive(Clone, Copy, Debug, ValueEnum)]
+use crate::loader;
+
+#[derive(Clone, Debug)]
 pub enum Language {
     #[cfg(feature = "rust")]
     Rust,
@@ -17,10 +20,17 @@ pub enum Language {
     PHP,
     #[cfg(feature = "php")]
     PHPOnly,
+    /// A grammar resolved at runtime via the loader subsystem, rather than
+    /// compiled in behind a feature flag.
+    Dynamic {
+        name: &'static str,
+        ts_lang: TSLanguage,
+        extensions: &'static [&'static str],
+    },
 }
 
 impl Language {
-    pub fn name(self) -> &'static str {
+    pub fn name(&self) -> &'static str {
         use Language::*;
 
         match self {
@@ -36,27 +46,65 @@ impl Language {
             PHP => "php",
             #[cfg(feature = "php")]
             PHPOnly => "php",
+            Dynamic { name, .. } => name,
         }
     }
 
-    pub fn ts_lang(self) -> TSLanguage {
+    pub fn ts_lang(&self) -> TSLanguage {
         use Language::*;
         match self {
             #[cfg(feature = "rust")]
-            Rust => tree_sitter_rust::LANGUAGE,
+            Rust => tree_sitter_rust::LANGUAGE.into(),
             #[cfg(feature = "go")]
-            Go => tree_sitter_go::LANGUAGE,
+            Go => tree_sitter_go::LANGUAGE.into(),
             #[cfg(feature = "javascript")]
-            JS => tree_sitter_javascript::LANGUAGE,
+            JS => tree_sitter_javascript::LANGUAGE.into(),
             #[cfg(feature = "typescript")]
-            TS => tree_sitter_typescript::LANGUAGE_TYPESCRIPT,
+            TS => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
             #[cfg(feature = "typescript")]
-            TSX => tree_sitter_typescript::LANGUAGE_TSX,
+            TSX => tree_sitter_typescript::LANGUAGE_TSX.into(),
             #[cfg(feature = "php")]
-            PHP => tree_sitter_php::LANGUAGE_PHP,
+            PHP => tree_sitter_php::LANGUAGE_PHP.into(),
             #[cfg(feature = "php")]
-            PHPOnly => tree_sitter_php::LANGUAGE_PHP_ONLY,
+            PHPOnly => tree_sitter_php::LANGUAGE_PHP_ONLY.into(),
+            Dynamic { ts_lang, .. } => ts_lang.clone(),
+        }
+    }
+
+    /// The extensions `TypesBuilder` should select this language for, used
+    /// when the grammar isn't one `ignore`'s default type set already knows.
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            Language::Dynamic { extensions, .. } => extensions,
+            _ => &[],
         }
-        .into()
+    }
+}
+
+impl FromStr for Language {
+    type Err = color_eyre::eyre::Error;
+
+    /// Parses a built-in language name, falling back to the loader
+    /// subsystem to `dlopen` a grammar matching `s` at runtime.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use Language::*;
+
+        Ok(match s {
+            #[cfg(feature = "rust")]
+            "rust" => Rust,
+            #[cfg(feature = "go")]
+            "go" => Go,
+            #[cfg(feature = "javascript")]
+            "js" | "javascript" => JS,
+            #[cfg(feature = "typescript")]
+            "ts" | "typescript" => TS,
+            #[cfg(feature = "typescript")]
+            "tsx" => TSX,
+            #[cfg(feature = "php")]
+            "php" => PHP,
+            #[cfg(feature = "php")]
+            "php-only" => PHPOnly,
+            name => loader::loader().load(name)?.clone(),
+        })
     }
 }
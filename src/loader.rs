@@ -0,0 +1,150 @@
+use std::collections::HashMap;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use color_eyre::eyre::{eyre, Result};
+use parking_lot::Mutex;
+use serde::Deserialize;
+use tree_sitter::Language as TSLanguage;
+
+use crate::language::Language;
+
+/// A single grammar entry in the loader config, naming the extensions it
+/// should be selected for.
+#[derive(Deserialize, Debug, Clone)]
+pub struct GrammarConfig {
+    #[serde(default)]
+    pub extensions: Vec<String>,
+}
+
+/// On-disk config read by the loader: where to look for compiled grammars
+/// and which extensions map to which grammar name.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct LoaderConfig {
+    #[serde(default)]
+    pub parser_directories: Vec<PathBuf>,
+    #[serde(default)]
+    pub languages: HashMap<String, GrammarConfig>,
+}
+
+impl LoaderConfig {
+    fn path() -> Option<PathBuf> {
+        if let Ok(p) = env::var("TSFIND_LOADER_CONFIG") {
+            return Some(PathBuf::from(p));
+        }
+
+        let dir = dirs::config_dir()?.join("tsfind");
+        Some(dir.join("loader.toml"))
+    }
+
+    fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Ok(Self::default());
+        };
+
+        toml::from_str(&contents).map_err(|e| eyre!("{path:?}: failed to parse loader config").wrap_err(e))
+    }
+
+    /// The grammar name mapped to by a file extension, if any.
+    pub fn name_for_extension(&self, extension: &str) -> Option<&str> {
+        self.languages
+            .iter()
+            .find(|(_, cfg)| cfg.extensions.iter().any(|e| e == extension))
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Caches `dlopen`'d grammars for the process lifetime. Loaded libraries and
+/// the `Language`s built from them are leaked, matching the rest of the
+/// tool's `'static` state so `ts_lang()` stays cheap across the parallel
+/// walk.
+pub struct Loader {
+    config: LoaderConfig,
+    cache: Mutex<HashMap<String, &'static Language>>,
+}
+
+static LOADER: OnceLock<Loader> = OnceLock::new();
+
+pub fn loader() -> &'static Loader {
+    LOADER.get_or_init(|| Loader {
+        config: LoaderConfig::load().unwrap_or_default(),
+        cache: Mutex::new(HashMap::new()),
+    })
+}
+
+impl Loader {
+    pub fn config(&self) -> &LoaderConfig {
+        &self.config
+    }
+
+    /// Loads (or returns the cached) dynamic grammar named `name`.
+    pub fn load(&self, name: &str) -> Result<&'static Language> {
+        if let Some(lang) = self.cache.lock().get(name) {
+            return Ok(lang);
+        }
+
+        let ts_lang = self.dlopen(name)?;
+        let extensions: &'static [&'static str] = Box::leak(
+            self.config
+                .languages
+                .get(name)
+                .map(|cfg| cfg.extensions.iter().map(|s| s.as_str().to_owned().leak() as &str))
+                .into_iter()
+                .flatten()
+                .collect::<Box<[_]>>(),
+        );
+
+        let lang = Box::leak(Box::new(Language::Dynamic {
+            name: name.to_owned().leak(),
+            ts_lang,
+            extensions,
+        }));
+
+        self.cache.lock().insert(name.to_owned(), lang);
+        Ok(lang)
+    }
+
+    fn dlopen(&self, name: &str) -> Result<TSLanguage> {
+        let file_names = [
+            format!("libtree-sitter-{name}.so"),
+            format!("libtree-sitter-{name}.dylib"),
+            format!("tree-sitter-{name}.dll"),
+        ];
+
+        let candidate = self
+            .config
+            .parser_directories
+            .iter()
+            .flat_map(|dir| file_names.iter().map(move |f| dir.join(f)))
+            .find(|path| path.is_file())
+            .ok_or_else(|| {
+                eyre!(
+                    "no compiled grammar named {name:?} found in parser_directories {:?}",
+                    self.config.parser_directories
+                )
+            })?;
+
+        self.dlopen_path(&candidate, name)
+    }
+
+    fn dlopen_path(&self, path: &Path, name: &str) -> Result<TSLanguage> {
+        // Leaked so the resolved symbol (and the `TSLanguage` it produces)
+        // remains valid for the process lifetime.
+        let lib = Box::leak(Box::new(unsafe {
+            libloading::Library::new(path).map_err(|e| eyre!("{path:?}: failed to load grammar").wrap_err(e))?
+        }));
+
+        let symbol = format!("tree_sitter_{}\0", name.replace('-', "_"));
+        let language_fn: libloading::Symbol<unsafe extern "C" fn() -> *const tree_sitter::ffi::TSLanguage> = unsafe {
+            lib.get(symbol.as_bytes())
+                .map_err(|e| eyre!("{path:?}: missing symbol {symbol:?}").wrap_err(e))?
+        };
+
+        Ok(unsafe { TSLanguage::from_raw(language_fn()) })
+    }
+}
@@ -1,3 +1,4 @@
+use ariadne::{Label, Report, ReportKind, Source};
 use bstr::ByteSlice;
 use clap::Parser;
 use color_eyre::{
@@ -8,21 +9,52 @@ use ignore::{types::TypesBuilder, WalkBuilder};
 use language::Language;
 use memmap2::Mmap;
 use parking_lot::Mutex;
-use std::{borrow::Cow, fmt::Write};
+use std::{borrow::Cow, fmt::Write as _};
 use std::{
-    convert::Infallible, fs::File, num::NonZeroUsize, os::unix::ffi::OsStrExt, path::Path,
-    sync::Arc,
+    convert::Infallible,
+    fs::File,
+    num::NonZeroUsize,
+    os::unix::ffi::OsStrExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::mpsc::{self, Sender},
 };
 
 use tree_sitter::{Language as TSLanguage, Parser as TSParser, Query, QueryCursor};
 
+mod detect;
+mod highlight;
 mod language;
+mod loader;
+mod predicate;
+mod replace;
+mod sink;
+
+use predicate::CompiledPredicates;
+use sink::{Format, Msg};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 struct Args {
-    language: Language,
-    paths: Vec<Box<Path>>,
+    #[arg(
+        value_name = "LANGUAGE PATHS...",
+        help = "Language to parse as, followed by the paths to search (omit the language with --auto)"
+    )]
+    positionals: Vec<String>,
+
+    #[arg(
+        short = 'a',
+        long,
+        help = "Detect each file's language from its extension instead of requiring one language for every file"
+    )]
+    auto: bool,
 
     #[arg(short = 'q', long, help = "The query to find matches for", value_parser = leak_str)]
     query: Option<&'static str>,
@@ -43,9 +75,45 @@ struct Args {
     #[arg(short = 't', long, help = "Only report captured text")]
     only_text: bool,
 
+    #[arg(
+        short = 'p',
+        long,
+        help = "Pretty-print matches with underlined source context"
+    )]
+    pretty: bool,
+
     #[arg(short = 'l', long, help = "Only report files with matches")]
     list: bool,
 
+    #[arg(
+        long,
+        help = "Emit the historical single JSON array instead of one JSON object per match per line"
+    )]
+    json_array: bool,
+
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = ColorMode::Auto,
+        help = "Colorize captured text and --pretty underlines by node kind"
+    )]
+    color: ColorMode,
+
+    #[arg(
+        short = 'r',
+        long,
+        help = "Rewrite each match's `target` capture using this template; interpolate other captures with ${name}",
+        value_parser = leak_str,
+    )]
+    replace: Option<&'static str>,
+
+    #[arg(
+        long,
+        help = "With --replace, print a unified diff instead of writing changes to disk",
+        requires = "replace"
+    )]
+    dry_run: bool,
+
     #[arg(
         short = 's',
         long,
@@ -65,21 +133,165 @@ fn unescape_and_leak_str(s: &str) -> Result<&'static str, unescaper::Error> {
     unescaper::unescape(s).map(|s| s.leak() as _)
 }
 
+#[derive(Clone, Copy)]
+struct CachedQuery {
+    query: &'static Query,
+    captures: &'static [&'static str],
+    /// The index of the query's `target` capture, resolved once up front so
+    /// `--replace` without one fails before any file is touched instead of
+    /// once per matching file.
+    target_idx: Option<u32>,
+}
+
+/// Per-file-invariant output flags, bundled so `parse` takes one value
+/// instead of a growing list of positional bools.
+#[derive(Clone, Copy)]
+struct Options {
+    hidden_captures: bool,
+    only_text: bool,
+    pretty: bool,
+    list: bool,
+    replace: Option<&'static str>,
+    dry_run: bool,
+    use_color: bool,
+}
+
+/// Compiles a query against each language it's actually needed for at most
+/// once, so that in `--auto` mode a query that doesn't parse against a given
+/// grammar is reported once per language rather than once per file.
+#[derive(Default)]
+struct QueryCache {
+    by_language: Mutex<std::collections::HashMap<(&'static str, std::mem::Discriminant<Language>), Option<CachedQuery>>>,
+}
+
+impl QueryCache {
+    fn get_or_compile(
+        &self,
+        language: &Language,
+        query_src: &'static str,
+        replace_requested: bool,
+    ) -> Option<CachedQuery> {
+        let name = language.name();
+        // TS and TSX share a name but not a grammar, so the variant is part
+        // of the key too. The lock is held while compiling so that parallel
+        // workers never compile (or report) the same language twice.
+        let key = (name, std::mem::discriminant(language));
+        let mut by_language = self.by_language.lock();
+
+        if let Some(cached) = by_language.get(&key) {
+            return *cached;
+        }
+
+        let compiled = (|| {
+            let query = match Query::new(&language.ts_lang(), query_src) {
+                Ok(q) => q,
+                Err(e) => {
+                    eprintln!("{name}: error parsing query: {e}");
+                    return None;
+                }
+            };
+
+            if let Err(e) = CompiledPredicates::compile(&query) {
+                eprintln!("{name}: error compiling query predicates: {e}");
+                return None;
+            }
+
+            let captures: &'static [&'static str] = Box::leak(
+                query
+                    .capture_names()
+                    .iter()
+                    .map(|&n| Box::leak(<Box<str>>::from(n)) as _)
+                    .collect::<Box<[_]>>(),
+            );
+
+            let target_idx = if replace_requested {
+                match captures.iter().position(|&c| c == "target") {
+                    Some(idx) => Some(idx as u32),
+                    None => {
+                        eprintln!("{name}: --replace requires the query to have a capture named `target`");
+                        return None;
+                    }
+                }
+            } else {
+                None
+            };
+
+            Some(CachedQuery {
+                query: Box::leak(Box::new(query)) as &'static _,
+                captures,
+                target_idx,
+            })
+        })();
+
+        by_language.insert(key, compiled);
+        compiled
+    }
+}
+
 fn main() -> Result<()> {
     color_eyre::install()?;
 
     let Args {
-        language,
+        positionals,
+        auto,
         query,
         query_file,
-        mut paths,
         hidden,
         hidden_captures,
         only_text,
+        pretty,
         list,
+        json_array,
+        color,
+        replace,
+        dry_run,
         separator,
     } = Args::parse();
 
+    let (language, mut paths): (Option<Language>, Vec<Box<Path>>) = if auto {
+        (
+            None,
+            positionals.into_iter().map(|p| Box::<Path>::from(PathBuf::from(p))).collect(),
+        )
+    } else {
+        let mut positionals = positionals.into_iter();
+        let Some(language) = positionals.next() else {
+            return Err(eyre!(
+                "the following required argument was not provided: <LANGUAGE>"
+            ));
+        };
+        let language = Language::from_str(&language)?;
+        let paths = positionals.map(|p| Box::<Path>::from(PathBuf::from(p))).collect();
+        (Some(language), paths)
+    };
+
+    let use_color = match color {
+        ColorMode::Always => true,
+        ColorMode::Never => false,
+        ColorMode::Auto => console::Term::stdout().is_term(),
+    };
+    console::set_colors_enabled(use_color);
+
+    let options = Options {
+        hidden_captures,
+        only_text,
+        pretty,
+        list,
+        replace,
+        dry_run,
+        use_color,
+    };
+
+    let format = if pretty || replace.is_some() {
+        Format::Raw
+    } else if list || only_text {
+        Format::Joined
+    } else if json_array {
+        Format::JsonArray
+    } else {
+        Format::Ndjson
+    };
+
     let query_src = match (query, query_file) {
         (None, None) => return Err(eyre!("specify either a query or query file with -q/-Q")),
         (Some(..), Some(..)) => {
@@ -103,42 +315,86 @@ fn main() -> Result<()> {
     };
 
     if query_src.is_empty() {
-        if !list && !only_text {
+        if format == Format::JsonArray {
             println!("[]");
         }
         return Ok(());
     }
 
-    let query = match Query::new(&language.ts_lang(), query_src) {
-        Ok(q) => q,
-        Err(e) => return Err(eyre!("error parsing query").error(e)),
-    };
-
-    let query_captures: &'static [&'static str] = Box::leak(
-        query
-            .capture_names()
-            .iter()
-            .map(|&n| Box::leak(<Box<str>>::from(n)) as _)
-            .collect::<Box<[_]>>(),
-    );
-
-    let out = Arc::new(Mutex::new(String::new()));
-
     if paths.is_empty() {
         paths.push(Path::new("./").into());
     }
 
-    let query = Box::leak(Box::new(query)) as &'static _;
+    let mut types_builder = TypesBuilder::new();
+    types_builder.add_defaults();
+
+    let single: Option<CachedQuery> = if auto {
+        for name in detect::enabled_language_names() {
+            types_builder.select(name);
+        }
+        for (name, cfg) in &loader::loader().config().languages {
+            for ext in &cfg.extensions {
+                types_builder.add(name, &format!("*.{ext}"))?;
+            }
+            types_builder.select(name);
+        }
+        None
+    } else {
+        let language = language.as_ref().expect("language is parsed from the first positional unless --auto");
+
+        for ext in language.extensions() {
+            types_builder.add(language.name(), &format!("*.{ext}"))?;
+        }
+        types_builder.select(language.name());
+
+        let query = match Query::new(&language.ts_lang(), query_src) {
+            Ok(q) => q,
+            Err(e) => return Err(eyre!("error parsing query").error(e)),
+        };
+
+        if let Err(e) = CompiledPredicates::compile(&query) {
+            return Err(eyre!("error compiling query predicates: {e:?}"));
+        }
+
+        let query_captures: &'static [&'static str] = Box::leak(
+            query
+                .capture_names()
+                .iter()
+                .map(|&n| Box::leak(<Box<str>>::from(n)) as _)
+                .collect::<Box<[_]>>(),
+        );
+
+        let target_idx = if replace.is_some() {
+            match query_captures.iter().position(|&c| c == "target") {
+                Some(idx) => Some(idx as u32),
+                None => {
+                    return Err(eyre!(
+                        "--replace requires the query to have a capture named `target`"
+                    ))
+                }
+            }
+        } else {
+            None
+        };
+
+        Some(CachedQuery {
+            query: Box::leak(Box::new(query)) as &'static _,
+            captures: query_captures,
+            target_idx,
+        })
+    };
+
+    let types = types_builder.build()?;
 
-    let types = TypesBuilder::new()
-        .add_defaults()
-        .select(language.name())
-        .build()?;
+    let cache: &'static QueryCache = Box::leak(Box::new(QueryCache::default()));
 
     let threads = std::thread::available_parallelism()
         .map(NonZeroUsize::get)
         .unwrap_or(1);
 
+    let (tx, rx) = mpsc::channel::<Msg>();
+    let writer = std::thread::spawn(move || sink::write_all(rx, format, separator));
+
     for path in paths {
         WalkBuilder::new(path)
             .parents(!hidden)
@@ -151,7 +407,8 @@ fn main() -> Result<()> {
             .threads(threads)
             .build_parallel()
             .run(|| {
-                let out = out.clone();
+                let tx = tx.clone();
+                let language = language.clone();
                 Box::new(move |file| {
                     use ignore::WalkState::*;
 
@@ -163,16 +420,29 @@ fn main() -> Result<()> {
                         return Continue;
                     }
 
+                    let (resolved, cached) = if auto {
+                        let Some(ext) = file.path().extension().and_then(|e| e.to_str()) else {
+                            return Continue;
+                        };
+                        let Some(language) = detect::language_for_extension(ext) else {
+                            return Continue;
+                        };
+                        let Some(cached) = cache.get_or_compile(&language, query_src, options.replace.is_some()) else {
+                            return Continue;
+                        };
+                        (language, cached)
+                    } else {
+                        (language.clone().unwrap(), single.unwrap())
+                    };
+
                     if let Err(e) = parse(
                         file.path(),
-                        &language.ts_lang(),
-                        query,
-                        query_captures,
-                        out.clone(),
-                        hidden_captures,
-                        only_text,
-                        list,
-                        separator,
+                        &resolved.ts_lang(),
+                        cached.query,
+                        cached.captures,
+                        cached.target_idx,
+                        &tx,
+                        options,
                     ) {
                         eprintln!("{e:?}");
                     }
@@ -182,16 +452,8 @@ fn main() -> Result<()> {
             })
     }
 
-    let out = Arc::into_inner(out).unwrap().into_inner();
-    if list || only_text {
-        if out.is_empty() {
-            return Ok(());
-        }
-
-        println!("{}", out.strip_suffix(separator).unwrap_or(&out));
-    } else {
-        println!("[{out}]");
-    }
+    drop(tx);
+    writer.join().unwrap()?;
 
     Ok(())
 }
@@ -201,12 +463,20 @@ fn parse(
     language: &TSLanguage,
     query: &Query,
     query_captures: &[&str],
-    out: Arc<Mutex<String>>,
-    hidden_captures: bool,
-    only_text: bool,
-    list: bool,
-    separator: &str,
+    target_idx: Option<u32>,
+    tx: &Sender<Msg>,
+    options: Options,
 ) -> Result<()> {
+    let Options {
+        hidden_captures,
+        only_text,
+        pretty,
+        list,
+        replace,
+        dry_run,
+        use_color,
+    } = options;
+
     let Ok(file) = std::fs::File::open(path) else {
         return Err(eyre!("{path:?}: failed to read file"));
     };
@@ -226,34 +496,58 @@ fn parse(
 
     let mut path_buf: Option<Cow<'_, str>> = None;
     let mut cursor = QueryCursor::new();
-
-    for (captures, idx) in cursor.captures(query, tree.root_node(), src) {
-        if !hidden_captures && query_captures[idx].starts_with("_") {
+    type TokenSpan = (std::ops::Range<usize>, &'static str, bool);
+    let mut labels: Vec<(std::ops::Range<usize>, &str, Vec<TokenSpan>)> = Vec::new();
+    let mut edits: Vec<(usize, usize, String)> = Vec::new();
+
+    for m in cursor.matches(query, tree.root_node(), src) {
+        if let (Some(template), Some(target_idx)) = (replace, target_idx) {
+            for node in m.nodes_for_capture_index(target_idx) {
+                let replacement = replace::interpolate(template, &m, query_captures, src);
+                edits.push((node.start_byte(), node.end_byte(), replacement));
+            }
             continue;
         }
 
-        let mut nodes = captures.nodes_for_capture_index(idx as u32);
-        if list && nodes.next().is_some() {
-            let path_bytes = path.as_os_str().as_bytes();
-            write!(
-                out.lock(),
-                "{}{separator}",
-                path_bytes
+        for capture in m.captures {
+            let idx = capture.index as usize;
+            let node = capture.node;
+
+            if !hidden_captures && query_captures[idx].starts_with("_") {
+                continue;
+            }
+
+            if pretty {
+                let tokens = highlight::leaves(node)
+                    .into_iter()
+                    .map(|leaf| (leaf.start_byte()..leaf.end_byte(), leaf.kind(), leaf.is_named()))
+                    .collect();
+                labels.push((node.start_byte()..node.end_byte(), query_captures[idx], tokens));
+                continue;
+            }
+
+            if list {
+                let path_bytes = path.as_os_str().as_bytes();
+                let path_str = path_bytes
                     .strip_prefix(b"./")
                     .unwrap_or(path_bytes)
-                    .to_str_lossy(),
-            )?;
-            return Ok(());
-        }
+                    .to_str_lossy();
+                let _ = tx.send(Msg::Item(path_str.into_owned()));
+                return Ok(());
+            }
 
-        for node in nodes {
             let Ok(text) = node.utf8_text(src) else {
                 eprintln!("{path:?}: found match that is not valid UTF-8");
                 continue;
             };
 
             if only_text {
-                write!(out.lock(), "{text}{separator}")?;
+                let text = if use_color {
+                    highlight::highlight_text(node, src)
+                } else {
+                    text.to_owned()
+                };
+                let _ = tx.send(Msg::Item(text));
                 continue;
             }
 
@@ -268,13 +562,9 @@ fn parse(
                     .to_str_lossy()
             });
 
-            let mut out = out.lock();
-            if !out.is_empty() {
-                out.push(',');
-            }
-
+            let mut item = String::new();
             write!(
-                out,
+                item,
                 r#"{{"file":{file:?},"start":{{"row":{srow},"column":{scol}}},"end":{{"row":{erow},"column":{ecol}}},"capture":{capture:?},"text":{text:?}}}"#,
                 file = path_buf,
                 srow = start.row,
@@ -283,7 +573,62 @@ fn parse(
                 ecol = end.column,
                 capture = query_captures[idx],
             )?;
+            let _ = tx.send(Msg::Item(item));
+        }
+    }
+
+    if replace.is_some() && !edits.is_empty() {
+        let buf = replace::apply_edits(src, edits)
+            .map_err(|e| eyre!("{path:?}: failed to apply --replace edits: {e:?}"))?;
+
+        if dry_run {
+            let path_bytes = path.as_os_str().as_bytes();
+            let path_str = path_bytes
+                .strip_prefix(b"./")
+                .unwrap_or(path_bytes)
+                .to_str_lossy();
+
+            let old = String::from_utf8_lossy(src);
+            let new = String::from_utf8_lossy(&buf);
+            let diff = similar::TextDiff::from_lines(old.as_ref(), new.as_ref());
+            let udiff = diff.unified_diff().header(&path_str, &path_str).to_string();
+            let _ = tx.send(Msg::Block(udiff));
+        } else {
+            std::fs::write(path, &buf)
+                .map_err(|e| eyre!("{path:?}: failed to write file").error(e))?;
+        }
+    }
+
+    if pretty && !labels.is_empty() {
+        let Ok(src_str) = std::str::from_utf8(src) else {
+            return Err(eyre!("{path:?}: found match that is not valid UTF-8"));
+        };
+
+        let path_bytes = path.as_os_str().as_bytes();
+        let path_str = path_bytes
+            .strip_prefix(b"./")
+            .unwrap_or(path_bytes)
+            .to_str_lossy();
+
+        let mut report = Report::build(ReportKind::Advice, &*path_str, 0)
+            .with_config(ariadne::Config::default().with_color(use_color));
+        for (range, name, tokens) in labels {
+            report = report.with_label(Label::new((&*path_str, range)).with_message(name));
+            if use_color {
+                for (token_range, kind, named) in tokens {
+                    report = report.with_label(
+                        Label::new((&*path_str, token_range)).with_color(highlight::ariadne_color(kind, named)),
+                    );
+                }
+            }
         }
+
+        let mut buf = Vec::new();
+        report
+            .finish()
+            .write((&*path_str, Source::from(src_str)), &mut buf)?;
+
+        let _ = tx.send(Msg::Block(String::from_utf8_lossy(&buf).into_owned()));
     }
 
     Ok(())
@@ -0,0 +1,61 @@
+use color_eyre::eyre::{eyre, Result};
+use tree_sitter::Query;
+
+/// Validates that a query only uses predicates tree-sitter itself knows how
+/// to evaluate.
+///
+/// tree-sitter's Rust binding already evaluates the standard text predicates
+/// (`#eq?`/`#not-eq?`, `#match?`/`#not-match?`, `#any-of?`/`#not-any-of?`)
+/// for us: `Query::new` parses them into typed `TextPredicateCapture`s, and
+/// `QueryCursor::matches`/`captures` applies them against whatever
+/// `TextProvider` they're given (the mmap'd `src` bytes, here) before a
+/// `QueryMatch` is ever produced. Because they're typed, they never show up
+/// in `Query::general_predicates()` — only genuinely unrecognized predicates
+/// do. So the only thing left for us to check is that a query doesn't lean
+/// on some predicate neither tree-sitter nor we know about, so a typo like
+/// `#mach?` fails loudly at query-load time instead of silently matching
+/// everything.
+pub struct CompiledPredicates;
+
+impl CompiledPredicates {
+    pub fn compile(query: &Query) -> Result<Self> {
+        for pattern_index in 0..query.pattern_count() {
+            if let Some(predicate) = query.general_predicates(pattern_index).first() {
+                return Err(eyre!("unknown query predicate `#{}`", predicate.operator));
+            }
+        }
+
+        Ok(Self)
+    }
+}
+
+#[cfg(all(test, feature = "rust"))]
+mod tests {
+    use tree_sitter::Query;
+
+    use super::*;
+
+    #[test]
+    fn compile_accepts_standard_text_predicates() {
+        let language = tree_sitter_rust::LANGUAGE.into();
+        let query = Query::new(
+            &language,
+            r#"(function_item name: (identifier) @name (#match? @name "^b"))"#,
+        )
+        .unwrap();
+
+        assert!(CompiledPredicates::compile(&query).is_ok());
+    }
+
+    #[test]
+    fn compile_rejects_unknown_predicate() {
+        let language = tree_sitter_rust::LANGUAGE.into();
+        let query = Query::new(
+            &language,
+            r#"(function_item name: (identifier) @name (#frobnicate? @name "foo"))"#,
+        )
+        .unwrap();
+
+        assert!(CompiledPredicates::compile(&query).is_err());
+    }
+}
@@ -0,0 +1,137 @@
+use color_eyre::eyre::{eyre, Result};
+use tree_sitter::QueryMatch;
+
+/// Expands `${name}` placeholders in a `--replace` template with the text of
+/// the same match's capture named `name`, leaving unknown placeholders and
+/// unmatched captures empty.
+pub fn interpolate(template: &str, m: &QueryMatch, query_captures: &[&str], src: &[u8]) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${") {
+        out.push_str(&rest[..start]);
+
+        let after = &rest[start + 2..];
+        let Some(end) = after.find('}') else {
+            out.push_str(&rest[start..]);
+            rest = "";
+            break;
+        };
+
+        let name = &after[..end];
+        if let Some(idx) = query_captures.iter().position(|&c| c == name) {
+            if let Some(node) = m.nodes_for_capture_index(idx as u32).next() {
+                if let Ok(text) = node.utf8_text(src) {
+                    out.push_str(text);
+                }
+            }
+        }
+
+        rest = &after[end + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Applies `(start_byte, end_byte, replacement)` edits to an owned copy of
+/// `src`. Edits must not overlap; they're applied from the end of the buffer
+/// backwards so earlier offsets stay valid as later ones are spliced in.
+pub fn apply_edits(src: &[u8], mut edits: Vec<(usize, usize, String)>) -> Result<Vec<u8>> {
+    edits.sort_by_key(|&(start, _, _)| start);
+
+    for pair in edits.windows(2) {
+        let [(_, prev_end, _), (next_start, ..)] = pair else {
+            unreachable!()
+        };
+        if next_start < prev_end {
+            return Err(eyre!("overlapping edits in the same file"));
+        }
+    }
+
+    let mut buf = src.to_vec();
+    for (start, end, replacement) in edits.into_iter().rev() {
+        buf.splice(start..end, replacement.into_bytes());
+    }
+
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_edits_splices_back_to_front() {
+        let src = b"one two three";
+        let edits = vec![
+            (0, 3, "1".to_owned()),
+            (4, 7, "2".to_owned()),
+            (8, 13, "3".to_owned()),
+        ];
+
+        let out = apply_edits(src, edits).unwrap();
+        assert_eq!(out, b"1 2 3");
+    }
+
+    #[test]
+    fn apply_edits_rejects_overlapping_ranges() {
+        let src = b"one two three";
+        let edits = vec![(0, 5, "a".to_owned()), (3, 7, "b".to_owned())];
+
+        assert!(apply_edits(src, edits).is_err());
+    }
+
+    #[test]
+    fn apply_edits_tolerates_unsorted_input() {
+        let src = b"one two three";
+        let edits = vec![(8, 13, "3".to_owned()), (0, 3, "1".to_owned())];
+
+        let out = apply_edits(src, edits).unwrap();
+        assert_eq!(out, b"1 two 3");
+    }
+}
+
+#[cfg(all(test, feature = "rust"))]
+mod interpolate_tests {
+    use tree_sitter::{Parser, Query, QueryCursor};
+
+    use super::*;
+
+    const SRC: &str = "fn foo() {}";
+    const QUERY: &str = "(function_item name: (identifier) @name)";
+
+    fn interpolate_against_first_match(template: &str) -> String {
+        let language = tree_sitter_rust::LANGUAGE.into();
+
+        let mut parser = Parser::new();
+        parser.set_language(&language).unwrap();
+        let tree = parser.parse(SRC, None).unwrap();
+
+        let query = Query::new(&language, QUERY).unwrap();
+        let query_captures: Vec<&str> = query.capture_names().to_vec();
+
+        let mut cursor = QueryCursor::new();
+        let m = cursor
+            .matches(&query, tree.root_node(), SRC.as_bytes())
+            .next()
+            .unwrap();
+
+        interpolate(template, &m, &query_captures, SRC.as_bytes())
+    }
+
+    #[test]
+    fn substitutes_known_capture() {
+        assert_eq!(interpolate_against_first_match("fn ${name}() {}"), "fn foo() {}");
+    }
+
+    #[test]
+    fn leaves_unknown_placeholder_empty() {
+        assert_eq!(interpolate_against_first_match("${missing}-${name}"), "-foo");
+    }
+
+    #[test]
+    fn passes_through_unterminated_placeholder() {
+        assert_eq!(interpolate_against_first_match("${name}-${oops"), "foo-${oops");
+    }
+}
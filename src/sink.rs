@@ -0,0 +1,73 @@
+use std::io::{self, Write};
+use std::sync::mpsc::Receiver;
+
+/// One unit of output produced by a worker thread and handed to the writer.
+pub enum Msg {
+    /// A single match, joined/wrapped by the writer according to `Format`.
+    Item(String),
+    /// An already fully-formatted chunk (a pretty report, a diff) printed
+    /// as-is, bypassing whatever joining `Format` would otherwise apply.
+    Block(String),
+}
+
+/// How the writer joins and wraps the `Item`s it receives.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One path/captured-text item per `separator`-joined line, as used by
+    /// `--list`/`--only-text`.
+    Joined,
+    /// The historical single JSON array, `[` and `]` around comma-joined
+    /// objects. Requires holding the opening bracket open until the walk
+    /// finishes, but each object is still streamed out as it arrives.
+    JsonArray,
+    /// One JSON object per match per line.
+    Ndjson,
+    /// Only `Block`s are expected; `Item`s are not produced by this mode
+    /// (used by `--pretty` and `--replace`).
+    Raw,
+}
+
+/// Drains `rx` on the current thread, writing each message to stdout as it
+/// arrives rather than buffering the whole result set in memory.
+pub fn write_all(rx: Receiver<Msg>, format: Format, separator: &str) -> io::Result<()> {
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    let mut wrote_any = false;
+
+    if format == Format::JsonArray {
+        write!(stdout, "[")?;
+    }
+
+    for msg in rx {
+        match msg {
+            Msg::Block(s) => write!(stdout, "{s}")?,
+            Msg::Item(s) => {
+                match format {
+                    Format::Joined => {
+                        if wrote_any {
+                            write!(stdout, "{separator}")?;
+                        }
+                        write!(stdout, "{s}")?;
+                    }
+                    Format::JsonArray => {
+                        if wrote_any {
+                            write!(stdout, ",")?;
+                        }
+                        write!(stdout, "{s}")?;
+                    }
+                    Format::Ndjson => writeln!(stdout, "{s}")?,
+                    Format::Raw => write!(stdout, "{s}")?,
+                }
+                wrote_any = true;
+            }
+        }
+    }
+
+    if format == Format::JsonArray {
+        writeln!(stdout, "]")?;
+    } else if format == Format::Joined && wrote_any {
+        writeln!(stdout)?;
+    }
+
+    Ok(())
+}